@@ -1,19 +1,81 @@
 use log::debug;
 use serde::{Deserialize, Serialize};
 use serde_yaml::{self};
+use std::process::Command;
+
+use crate::error::Error;
+use crate::filter::PathFilter;
+
+/// Either an inline plaintext password, or a command whose stdout (trimmed)
+/// is used as the password. `#[serde(untagged)]` picks whichever variant
+/// matches the fields present in the YAML, so existing configs that only
+/// set `password` keep working unchanged.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum PasswordSource {
+    Inline { password: String },
+    Command { password_command: String },
+}
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Configuration {
     pub address: String,
     pub username: String,
-    pub password: String,
+    #[serde(flatten)]
+    pub password: PasswordSource,
     pub local_path: String,
+    /// Regex patterns a path must match to be synced. Empty means "match
+    /// everything not excluded".
+    #[serde(default)]
+    pub include: Vec<String>,
+    /// Regex patterns a path must not match to be synced; always wins over
+    /// `include`. Merged with a default ignore set for dotfiles and common
+    /// editor/transient artifacts.
+    #[serde(default)]
+    pub exclude: Vec<String>,
 }
 
-pub fn load_configuration(filepath: &str) -> Result<Configuration, Box<dyn std::error::Error>> {
+impl Configuration {
+    /// Resolves the configured password, running `password_command` if that
+    /// is how it was configured.
+    pub fn resolve_password(&self) -> Result<String, Box<dyn std::error::Error>> {
+        match &self.password {
+            PasswordSource::Inline { password } => Ok(password.clone()),
+            PasswordSource::Command { password_command } => {
+                debug!("resolving password via command: {}", password_command);
+                let output = Command::new("sh").arg("-c").arg(password_command).output()?;
+                if !output.status.success() {
+                    return Err(format!(
+                        "password_command '{}' exited with {}",
+                        password_command, output.status
+                    )
+                    .into());
+                }
+                let password = String::from_utf8(output.stdout)?.trim().to_string();
+                if password.is_empty() {
+                    return Err(format!(
+                        "password_command '{}' produced no output (locked keyring?)",
+                        password_command
+                    )
+                    .into());
+                }
+                Ok(password)
+            }
+        }
+    }
+
+    /// Compiles `include`/`exclude` into a `PathFilter`, ready to decide
+    /// whether a given path should be synced.
+    pub fn path_filter(&self) -> Result<PathFilter, Error> {
+        PathFilter::compile(&self.include, &self.exclude)
+    }
+}
+
+pub fn load_configuration(filepath: &str) -> Result<Configuration, Error> {
     debug!("loading configuration from {}", filepath);
     let f = std::fs::File::open(filepath)?;
-    serde_yaml::from_reader(f).map_err(|err| Box::new(err) as Box<dyn std::error::Error>)
+    let config = serde_yaml::from_reader(f)?;
+    Ok(config)
 }
 
 #[cfg(test)]
@@ -34,7 +96,10 @@ mod tests {
             "https://www.some_nextcloud_server.de"
         );
         assert_eq!(scrape_config.username, "IhrBenutzername");
-        assert_eq!(scrape_config.password, "IhrPasswort");
+        assert_eq!(
+            scrape_config.resolve_password().expect("Could not resolve password"),
+            "IhrPasswort"
+        );
         assert_eq!(
             scrape_config.local_path,
             "/tmp/test_nextcloud_upload_daemon"
@@ -58,4 +123,36 @@ mod tests {
             "Found valid file with name README.md?!?!?"
         );
     }
+
+    fn config_with_password_command(password_command: &str) -> Configuration {
+        let yaml = format!(
+            "address: https://www.some_nextcloud_server.de\n\
+             username: IhrBenutzername\n\
+             password_command: \"{}\"\n\
+             local_path: /tmp/test_nextcloud_upload_daemon\n",
+            password_command
+        );
+        serde_yaml::from_str(&yaml).expect("config with password_command should deserialize")
+    }
+
+    #[test]
+    fn resolve_password_runs_password_command_and_trims_its_output() {
+        let config = config_with_password_command("echo IhrPasswort");
+        assert_eq!(
+            config.resolve_password().expect("Could not resolve password"),
+            "IhrPasswort"
+        );
+    }
+
+    #[test]
+    fn resolve_password_errors_if_password_command_fails() {
+        let config = config_with_password_command("exit 1");
+        assert!(config.resolve_password().is_err());
+    }
+
+    #[test]
+    fn resolve_password_errors_if_password_command_produces_no_output() {
+        let config = config_with_password_command("true");
+        assert!(config.resolve_password().is_err());
+    }
 }
@@ -0,0 +1,64 @@
+use std::fmt;
+
+/// Crate-wide error type. Each variant names a distinct failure category so
+/// `main` can print an actionable message instead of a panic backtrace.
+#[derive(Debug)]
+pub enum Error {
+    ConfigNotFound(std::io::Error),
+    ConfigParse(serde_yaml::Error),
+    WatchSetup(notify::Error),
+    Upload(reqwest::Error),
+    UploadFailed(String),
+    AuthFailed(String),
+    InvalidFilterPattern(regex::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::ConfigNotFound(err) => write!(f, "could not read configuration file: {}", err),
+            Error::ConfigParse(err) => write!(f, "could not parse configuration file: {}", err),
+            Error::WatchSetup(err) => write!(f, "could not set up filesystem watch: {}", err),
+            Error::Upload(err) => write!(f, "upload request to Nextcloud failed: {}", err),
+            Error::UploadFailed(message) => write!(f, "upload to Nextcloud failed: {}", message),
+            Error::AuthFailed(message) => {
+                write!(f, "could not authenticate with Nextcloud: {}", message)
+            }
+            Error::InvalidFilterPattern(err) => {
+                write!(f, "invalid include/exclude pattern: {}", err)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::ConfigNotFound(err)
+    }
+}
+
+impl From<serde_yaml::Error> for Error {
+    fn from(err: serde_yaml::Error) -> Self {
+        Error::ConfigParse(err)
+    }
+}
+
+impl From<notify::Error> for Error {
+    fn from(err: notify::Error) -> Self {
+        Error::WatchSetup(err)
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(err: reqwest::Error) -> Self {
+        Error::Upload(err)
+    }
+}
+
+impl From<regex::Error> for Error {
+    fn from(err: regex::Error) -> Self {
+        Error::InvalidFilterPattern(err)
+    }
+}
@@ -0,0 +1,108 @@
+use log::debug;
+use regex::Regex;
+
+use crate::error::Error;
+
+/// Exclude patterns applied even when the user sets no `exclude` list of
+/// their own: dotfiles/dot-directories and common editor or transient
+/// download artifacts.
+const DEFAULT_EXCLUDES: &[&str] = &[
+    r"(^|/)\.",
+    r"\.swp$",
+    r"\.swx$",
+    r"~$",
+    r"\.part$",
+    r"\.crdownload$",
+    r"\.tmp$",
+];
+
+/// Decides whether a path under the watched root should be synced, based on
+/// `include`/`exclude` regex lists compiled once at config load time.
+/// `exclude` always wins over `include`; a path is only skipped by
+/// `include` if the list is non-empty and nothing in it matches.
+#[derive(Debug)]
+pub struct PathFilter {
+    include: Vec<Regex>,
+    exclude: Vec<Regex>,
+}
+
+impl PathFilter {
+    /// Compiles `include` and `exclude` regex patterns, merging in
+    /// `DEFAULT_EXCLUDES` so editor/transient artifacts are ignored by
+    /// default.
+    pub fn compile(include: &[String], exclude: &[String]) -> Result<Self, Error> {
+        let mut exclude_patterns: Vec<String> =
+            DEFAULT_EXCLUDES.iter().map(|&p| p.to_string()).collect();
+        exclude_patterns.extend(exclude.iter().cloned());
+
+        Ok(PathFilter {
+            include: compile_all(include)?,
+            exclude: compile_all(&exclude_patterns)?,
+        })
+    }
+
+    /// Returns true if `relative_path` should be synced.
+    pub fn allows(&self, relative_path: &str) -> bool {
+        if self.exclude.iter().any(|re| re.is_match(relative_path)) {
+            debug!("{} excluded by filter", relative_path);
+            return false;
+        }
+        if self.include.is_empty() {
+            return true;
+        }
+        self.include.iter().any(|re| re.is_match(relative_path))
+    }
+}
+
+fn compile_all(patterns: &[String]) -> Result<Vec<Regex>, Error> {
+    patterns.iter().map(|pattern| Ok(Regex::new(pattern)?)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn empty_include_matches_everything_not_excluded() {
+        let filter = PathFilter::compile(&[], &[]).expect("patterns should compile");
+        assert!(filter.allows("photos/holiday.jpg"));
+        assert!(filter.allows("notes.txt"));
+    }
+
+    #[test]
+    fn default_excludes_dotfiles_and_editor_artifacts() {
+        let filter = PathFilter::compile(&[], &[]).expect("patterns should compile");
+        assert!(!filter.allows(".hidden"));
+        assert!(!filter.allows("docs/.git/config"));
+        assert!(!filter.allows("notes.txt.swp"));
+        assert!(!filter.allows("report.docx~"));
+        assert!(!filter.allows("download.part"));
+        assert!(filter.allows("notes.txt"));
+    }
+
+    #[test]
+    fn include_list_restricts_to_matching_paths() {
+        let filter = PathFilter::compile(&[r"\.jpg$".to_string()], &[])
+            .expect("patterns should compile");
+        assert!(filter.allows("photos/holiday.jpg"));
+        assert!(!filter.allows("notes.txt"));
+    }
+
+    #[test]
+    fn exclude_wins_over_include() {
+        let filter = PathFilter::compile(
+            &[r"\.jpg$".to_string()],
+            &[r"^private/".to_string()],
+        )
+        .expect("patterns should compile");
+        assert!(filter.allows("photos/holiday.jpg"));
+        assert!(!filter.allows("private/holiday.jpg"));
+    }
+
+    #[test]
+    fn invalid_pattern_is_rejected_at_compile_time() {
+        let result = PathFilter::compile(&["(".to_string()], &[]);
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,156 @@
+use log::{debug, warn};
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// A PID file under `$XDG_RUNTIME_DIR` that guarantees only one daemon
+/// instance runs at a time for a given config. Removed on `Drop`; a lock
+/// file left behind by an exit that skips destructors (`std::process::exit`,
+/// a fatal signal) is reclaimed by the next `acquire` once its pid is no
+/// longer alive.
+pub struct InstanceLock {
+    path: PathBuf,
+}
+
+impl InstanceLock {
+    /// Acquires the lock for `config_path`, deriving a unique lock file name
+    /// from it so daemons watching different configs don't collide. Returns
+    /// `Ok(None)` if another live instance already holds the lock, so the
+    /// caller can exit gracefully instead of erroring.
+    pub fn acquire(runtime_dir: &Path, config_path: &Path) -> Result<Option<Self>, std::io::Error> {
+        fs::create_dir_all(runtime_dir)?;
+        let lock_path = runtime_dir.join(format!("{}.lock", lock_name(config_path)));
+
+        // `create_new` makes the open+create atomic (fails with
+        // `AlreadyExists` if the file is already there), so two processes
+        // racing to acquire the lock can't both believe they won it the way
+        // a separate read-then-create would allow.
+        loop {
+            match fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&lock_path)
+            {
+                Ok(mut file) => {
+                    write!(file, "{}", std::process::id())?;
+                    debug!("acquired instance lock {:?}", lock_path);
+                    return Ok(Some(InstanceLock { path: lock_path }));
+                }
+                Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => {
+                    match read_pid(&lock_path) {
+                        Some(existing_pid) if process_is_alive(existing_pid) => return Ok(None),
+                        Some(existing_pid) => {
+                            warn!(
+                                "removing stale lock file {:?} left by pid {}",
+                                lock_path, existing_pid
+                            );
+                            fs::remove_file(&lock_path)?;
+                        }
+                        None => {
+                            // Unreadable/empty lock file left behind by a
+                            // crash before the pid was written; safe to
+                            // reclaim.
+                            fs::remove_file(&lock_path)?;
+                        }
+                    }
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+impl Drop for InstanceLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+fn lock_name(config_path: &Path) -> String {
+    let mut hasher = DefaultHasher::new();
+    config_path.hash(&mut hasher);
+    format!("{:x}", hasher.finish())
+}
+
+fn read_pid(lock_path: &Path) -> Option<u32> {
+    fs::read_to_string(lock_path).ok()?.trim().parse().ok()
+}
+
+#[cfg(target_os = "linux")]
+fn process_is_alive(pid: u32) -> bool {
+    Path::new(&format!("/proc/{}", pid)).exists()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn process_is_alive(_pid: u32) -> bool {
+    // Conservatively assume it is still running so we never silently steal
+    // a lock on platforms we have no way to check.
+    true
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn temp_runtime_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("nud_lock_test_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn acquire_twice_for_the_same_config_returns_none_the_second_time() {
+        let runtime_dir = temp_runtime_dir("acquire_twice");
+        let config_path = Path::new("/etc/example/config.yaml");
+
+        let first = InstanceLock::acquire(&runtime_dir, config_path)
+            .expect("first acquire should not error")
+            .expect("first acquire should succeed");
+
+        let second = InstanceLock::acquire(&runtime_dir, config_path)
+            .expect("second acquire should not error");
+        assert!(second.is_none());
+
+        drop(first);
+        fs::remove_dir_all(&runtime_dir).ok();
+    }
+
+    #[test]
+    fn acquire_reclaims_a_lock_file_left_by_a_dead_pid() {
+        let runtime_dir = temp_runtime_dir("reclaim_dead_pid");
+        let config_path = Path::new("/etc/example/config.yaml");
+        fs::create_dir_all(&runtime_dir).expect("failed to create runtime dir");
+
+        let lock_path = runtime_dir.join(format!("{}.lock", lock_name(config_path)));
+        // Far outside any plausible live PID range, to stand in for a
+        // process that has since exited without cleaning up its lock file.
+        fs::write(&lock_path, "999999999").expect("failed to seed stale lock file");
+
+        let lock = InstanceLock::acquire(&runtime_dir, config_path)
+            .expect("acquire should not error")
+            .expect("a stale lock should be reclaimed");
+
+        drop(lock);
+        fs::remove_dir_all(&runtime_dir).ok();
+    }
+
+    #[test]
+    fn drop_releases_the_lock_file() {
+        let runtime_dir = temp_runtime_dir("drop_releases");
+        let config_path = Path::new("/etc/example/config.yaml");
+
+        let lock = InstanceLock::acquire(&runtime_dir, config_path)
+            .expect("acquire should not error")
+            .expect("acquire should succeed");
+        let lock_path = runtime_dir.join(format!("{}.lock", lock_name(config_path)));
+        assert!(lock_path.is_file());
+
+        drop(lock);
+        assert!(!lock_path.is_file());
+
+        fs::remove_dir_all(&runtime_dir).ok();
+    }
+}
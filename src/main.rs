@@ -1,19 +1,199 @@
-use log::{debug, info};
-use std::{thread, time};
-//use notify::RecommendedWatcher;
+use clap::Parser;
+use log::{debug, error, info};
+use std::path::Path;
+use std::sync::{Arc, Mutex, RwLock};
 
 pub mod configuration;
+pub mod error;
+pub mod filter;
+pub mod lock;
+pub mod paths;
+pub mod reload;
+pub mod uploader;
+pub mod upload_queue;
 pub mod watcher;
 
+/// A daemon that uploads files written under a watched directory to a
+/// Nextcloud server over WebDAV.
+#[derive(Parser, Debug)]
+#[command(name = "nextcloud_upload_daemon")]
+struct Cli {
+    /// Path to the configuration file. Overrides the XDG config search.
+    #[arg(long)]
+    config: Option<String>,
+}
+
+/// Prints an actionable message and exits, instead of a panic backtrace.
+fn fail(context: &str, err: impl std::fmt::Display) -> ! {
+    eprintln!("{}: {}", context, err);
+    std::process::exit(1);
+}
+
 fn main() {
     env_logger::init();
     info!("starting up");
-    let scrape_config = configuration::load_configuration("login_credentials.yaml")
-        .expect("Could no load config file.");
-    debug!("got Configuration: {:?}", scrape_config);
-    let _directory_watcher = match watcher::create_watcher(scrape_config.local_path.as_str()) {
-        Err(_) => panic!("Could not watch"),
-        Ok(watcher) => watcher,
+
+    let cli = Cli::parse();
+    let config_path = paths::resolve_config_path(cli.config.as_deref()).unwrap_or_else(|| {
+        fail(
+            "could not find a configuration file",
+            "checked --config, $XDG_CONFIG_HOME, and $XDG_CONFIG_DIRS",
+        )
+    });
+
+    // Held for the rest of `main`'s lifetime; its `Drop` impl removes the
+    // lock file when `main` returns. Exits through `fail` skip `Drop` and
+    // leave a stale lock file that the next start reclaims.
+    let _instance_lock = match lock::InstanceLock::acquire(&paths::runtime_dir(), &config_path) {
+        Ok(Some(instance_lock)) => instance_lock,
+        Ok(None) => {
+            info!(
+                "another instance is already watching {:?}, exiting",
+                config_path
+            );
+            return;
+        }
+        Err(err) => fail("could not acquire the instance lock", err),
     };
-    thread::sleep(time::Duration::from_secs(60));
+
+    let config_path_str = config_path.to_string_lossy().into_owned();
+    let initial_config = configuration::load_configuration(&config_path_str)
+        .unwrap_or_else(|err| fail("could not load configuration", err));
+    debug!("got Configuration: {:?}", initial_config);
+
+    let local_path = Path::new(&initial_config.local_path);
+    if !local_path.is_dir() {
+        fail(
+            "invalid configuration",
+            format!("local_path {:?} does not exist or is not a directory", local_path),
+        );
+    }
+    // `notify` reports canonical event paths (see `WatchState::watch`), so
+    // `watched_root` must be canonical too, or `remote_path_for`'s
+    // `strip_prefix` fails on every event for a relative `local_path` or one
+    // with a symlink component.
+    let local_path = local_path.canonicalize().unwrap_or_else(|err| {
+        fail(
+            "invalid configuration",
+            format!("could not canonicalize local_path {:?}: {}", local_path, err),
+        )
+    });
+    let local_path = local_path.as_path();
+    let watched_root = Arc::new(Mutex::new(local_path.to_path_buf()));
+
+    let uploader = uploader::Uploader::new(&initial_config)
+        .unwrap_or_else(|err| fail("could not resolve Nextcloud credentials", err));
+    uploader
+        .verify_connection()
+        .unwrap_or_else(|err| fail("could not reach Nextcloud", err));
+    let uploader = Arc::new(Mutex::new(uploader));
+
+    let filter = initial_config
+        .path_filter()
+        .unwrap_or_else(|err| fail("invalid include/exclude configuration", err));
+    let filter = Arc::new(Mutex::new(filter));
+
+    let (mut dir_watcher, event_receiver) = watcher::create_watcher(initial_config.local_path.as_str())
+        .unwrap_or_else(|err| fail("could not set up the filesystem watch", err));
+
+    let mut state = watcher::WatchState::new();
+    state.watch(&mut dir_watcher, local_path);
+
+    let queue = Arc::new(
+        upload_queue::UploadQueue::open(paths::state_dir().join("upload_queue.journal"))
+            .unwrap_or_else(|err| fail("could not open the upload queue journal", err)),
+    );
+    upload_queue::spawn_worker(Arc::clone(&queue), Arc::clone(&uploader))
+        .unwrap_or_else(|err| fail("could not start the upload queue worker", err));
+
+    let dir_watcher = Arc::new(Mutex::new(dir_watcher));
+    let state = Arc::new(Mutex::new(state));
+    let config = Arc::new(RwLock::new(initial_config));
+
+    {
+        let dir_watcher = Arc::clone(&dir_watcher);
+        let state = Arc::clone(&state);
+        let config_for_closure = Arc::clone(&config);
+        let uploader = Arc::clone(&uploader);
+        let filter = Arc::clone(&filter);
+        let watched_root = Arc::clone(&watched_root);
+        reload::spawn(
+            config_path_str.clone(),
+            Arc::clone(&config),
+            move |new_config| {
+                let old_root = {
+                    let current = config_for_closure
+                        .read()
+                        .expect("configuration lock poisoned");
+                    Path::new(&current.local_path).to_path_buf()
+                };
+                let new_root = Path::new(&new_config.local_path).to_path_buf();
+                if old_root != new_root {
+                    info!(
+                        "local_path changed from {:?} to {:?}, moving the watch",
+                        old_root, new_root
+                    );
+                    let mut watcher_guard = dir_watcher.lock().expect("watcher lock poisoned");
+                    let mut state_guard = state.lock().expect("state lock poisoned");
+                    state_guard.forget(&mut watcher_guard, &old_root);
+                    state_guard.watch(&mut watcher_guard, &new_root);
+
+                    // Keep the canonical root in sync with what `notify` is
+                    // now actually watching, so `remote_path_for`'s
+                    // `strip_prefix` keeps matching event paths.
+                    match new_root.canonicalize() {
+                        Ok(canonical) => {
+                            *watched_root.lock().expect("watched_root lock poisoned") = canonical;
+                        }
+                        Err(err) => error!(
+                            "could not canonicalize new local_path {:?}, keeping the previous watched root: {}",
+                            new_root, err
+                        ),
+                    }
+                }
+
+                match uploader::Uploader::new(new_config) {
+                    Ok(new_uploader) => {
+                        *uploader.lock().expect("uploader lock poisoned") = new_uploader;
+                    }
+                    Err(err) => error!("could not rebuild uploader after reload: {}", err),
+                }
+
+                match new_config.path_filter() {
+                    Ok(new_filter) => {
+                        *filter.lock().expect("filter lock poisoned") = new_filter;
+                    }
+                    Err(err) => error!("could not rebuild path filter after reload: {}", err),
+                }
+            },
+        )
+        .unwrap_or_else(|err| fail("could not start the configuration reload watcher", err));
+    }
+
+    loop {
+        match event_receiver.recv() {
+            Ok(event) => {
+                let root = watched_root
+                    .lock()
+                    .expect("watched_root lock poisoned")
+                    .clone();
+
+                let mut watcher_guard = dir_watcher.lock().expect("watcher lock poisoned");
+                let mut state_guard = state.lock().expect("state lock poisoned");
+                let filter_guard = filter.lock().expect("filter lock poisoned");
+                watcher::event_dispatcher(
+                    &event,
+                    &root,
+                    &queue,
+                    &filter_guard,
+                    &mut state_guard,
+                    &mut watcher_guard,
+                );
+            }
+            Err(err) => {
+                error!("event channel closed, shutting down: {}", err);
+                break;
+            }
+        }
+    }
 }
@@ -0,0 +1,47 @@
+use std::path::PathBuf;
+
+const APP_NAME: &str = "nextcloud_upload_daemon";
+
+/// Resolves the configuration file to load, in priority order: an explicit
+/// `--config` override, then `$XDG_CONFIG_HOME`, then each directory in the
+/// colon-separated `$XDG_CONFIG_DIRS` (defaulting to `/etc/xdg`), per the
+/// XDG base directory spec.
+pub fn resolve_config_path(cli_override: Option<&str>) -> Option<PathBuf> {
+    if let Some(path) = cli_override {
+        return Some(PathBuf::from(path));
+    }
+
+    let mut candidates = Vec::new();
+    if let Some(config_home) = std::env::var_os("XDG_CONFIG_HOME") {
+        candidates.push(PathBuf::from(config_home));
+    } else if let Some(home) = std::env::var_os("HOME") {
+        candidates.push(PathBuf::from(home).join(".config"));
+    }
+
+    let config_dirs = std::env::var("XDG_CONFIG_DIRS").unwrap_or_else(|_| "/etc/xdg".to_string());
+    candidates.extend(config_dirs.split(':').map(PathBuf::from));
+
+    candidates
+        .into_iter()
+        .map(|dir| dir.join(APP_NAME).join("config.yaml"))
+        .find(|path| path.is_file())
+}
+
+/// The directory the daemon's own state (the upload queue journal) lives
+/// under, following `$XDG_STATE_HOME` (falling back to `~/.local/state`).
+pub fn state_dir() -> PathBuf {
+    std::env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state")))
+        .unwrap_or_else(|| PathBuf::from("/tmp"))
+        .join(APP_NAME)
+}
+
+/// The directory transient runtime files (the instance lock) live under,
+/// following `$XDG_RUNTIME_DIR` (falling back to `/tmp`).
+pub fn runtime_dir() -> PathBuf {
+    std::env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("/tmp"))
+        .join(APP_NAME)
+}
@@ -0,0 +1,103 @@
+use log::{error, info, warn};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use signal_hook::consts::SIGHUP;
+use signal_hook::iterator::Signals;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+
+use crate::configuration::{self, Configuration};
+
+/// Re-loads `config_path` and, on success, swaps it into `config` after
+/// letting `on_reload` react to the new configuration while the previous
+/// one is still readable (e.g. to compare `local_path`). On a parse error
+/// the previous good configuration is kept and only a warning is logged.
+fn reload(
+    config_path: &str,
+    config: &Arc<RwLock<Configuration>>,
+    on_reload: &Arc<Mutex<dyn FnMut(&Configuration) + Send>>,
+) {
+    match configuration::load_configuration(config_path) {
+        Ok(new_config) => {
+            info!("reloaded configuration from {}", config_path);
+            (on_reload.lock().expect("on_reload lock poisoned"))(&new_config);
+            *config.write().expect("configuration lock poisoned") = new_config;
+        }
+        Err(err) => warn!(
+            "keeping previous configuration, failed to reload {}: {}",
+            config_path, err
+        ),
+    }
+}
+
+/// Watches `config_path` for changes and listens for `SIGHUP`, re-loading
+/// `Configuration` on either trigger and calling `on_reload` with the new
+/// configuration so the caller can react to changes such as a different
+/// `local_path`. A malformed reload keeps the previous good configuration in
+/// place and only logs the parse error, instead of terminating the daemon.
+pub fn spawn<F>(
+    config_path: String,
+    config: Arc<RwLock<Configuration>>,
+    on_reload: F,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    F: FnMut(&Configuration) + Send + 'static,
+{
+    let on_reload: Arc<Mutex<dyn FnMut(&Configuration) + Send>> = Arc::new(Mutex::new(on_reload));
+
+    // Watching the config file's path directly only lasts until the first
+    // edit: editors and atomic-save libraries typically write a temp file
+    // and rename it over the original, which detaches an inotify watch from
+    // the original inode. Watch the parent directory instead and filter by
+    // filename, so the watch survives rename-over-original saves.
+    let config_file_name = Path::new(&config_path).file_name().map(|n| n.to_os_string());
+    let watch_dir = Path::new(&config_path)
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| PathBuf::from("."));
+
+    let (tx, rx) = mpsc::channel::<()>();
+    let mut file_watcher = notify::recommended_watcher(move |res| match res {
+        Ok(event) => {
+            let event: notify::Event = event;
+            let is_config_file = event
+                .paths
+                .iter()
+                .any(|path| path.file_name() == config_file_name.as_deref());
+            if is_config_file {
+                let _ = tx.send(());
+            }
+        }
+        Err(e) => error!("config watch error: {:?}", e),
+    })?;
+    file_watcher.watch(&watch_dir, RecursiveMode::NonRecursive)?;
+
+    let signals = Signals::new([SIGHUP])?;
+
+    {
+        let config = Arc::clone(&config);
+        let on_reload = Arc::clone(&on_reload);
+        let config_path = config_path.clone();
+        thread::Builder::new()
+            .name("config-reload-file".into())
+            .spawn(move || {
+                let _file_watcher = file_watcher; // keep alive for the thread's lifetime
+                while rx.recv().is_ok() {
+                    reload(&config_path, &config, &on_reload);
+                }
+            })?;
+    }
+
+    thread::Builder::new()
+        .name("config-reload-sighup".into())
+        .spawn(move || {
+            for _ in signals.forever() {
+                info!("received SIGHUP, reloading configuration");
+                reload(&config_path, &config, &on_reload);
+            }
+        })?;
+
+    Ok(())
+}
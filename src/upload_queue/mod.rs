@@ -0,0 +1,251 @@
+use log::{debug, error, info, warn};
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::uploader::Uploader;
+
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(64);
+
+/// A single file waiting to be uploaded: the local path the watcher picked
+/// up, and the remote path it was resolved to at enqueue time.
+#[derive(Debug, Clone)]
+pub struct PendingUpload {
+    pub local_path: PathBuf,
+    pub remote_path: String,
+}
+
+impl PendingUpload {
+    fn to_line(&self) -> String {
+        format!("{}\t{}", self.remote_path, self.local_path.display())
+    }
+
+    fn from_line(line: &str) -> Option<Self> {
+        let (remote_path, local_path) = line.split_once('\t')?;
+        Some(PendingUpload {
+            local_path: PathBuf::from(local_path),
+            remote_path: remote_path.to_string(),
+        })
+    }
+}
+
+/// A journal-backed FIFO of pending uploads. Every enqueue is appended to
+/// an on-disk journal before it is acted on, so a path that was queued
+/// before a crash is replayed on the next startup: delivery is at-least-once,
+/// never at-most-once.
+pub struct UploadQueue {
+    journal_path: PathBuf,
+    journal: Mutex<File>,
+    entries: Mutex<VecDeque<PendingUpload>>,
+}
+
+impl UploadQueue {
+    /// Opens (creating if necessary) the on-disk journal at `journal_path`
+    /// and replays any entries left over from a previous run.
+    pub fn open(journal_path: PathBuf) -> Result<Self, Box<dyn Error>> {
+        if let Some(parent) = journal_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let entries: VecDeque<PendingUpload> = if journal_path.exists() {
+            let file = File::open(&journal_path)?;
+            BufReader::new(file)
+                .lines()
+                .map_while(Result::ok)
+                .filter_map(|line| PendingUpload::from_line(&line))
+                .collect()
+        } else {
+            VecDeque::new()
+        };
+        if !entries.is_empty() {
+            info!(
+                "replaying {} pending upload(s) from {:?}",
+                entries.len(),
+                journal_path
+            );
+        }
+
+        let journal = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&journal_path)?;
+
+        Ok(UploadQueue {
+            journal_path,
+            journal: Mutex::new(journal),
+            entries: Mutex::new(entries),
+        })
+    }
+
+    /// Appends `pending` to the on-disk journal and the in-memory queue.
+    /// The `entries` lock is held across both the journal write and the
+    /// in-memory push so `persist` (which also takes `entries` first) can
+    /// never rewrite the journal from a snapshot that predates this append
+    /// — otherwise the rewrite could drop the line this just wrote.
+    pub fn enqueue(&self, pending: PendingUpload) -> Result<(), Box<dyn Error>> {
+        let mut entries = self.entries.lock().expect("entries lock poisoned");
+        let mut journal = self.journal.lock().expect("journal lock poisoned");
+        writeln!(journal, "{}", pending.to_line())?;
+        journal.flush()?;
+        drop(journal);
+        entries.push_back(pending);
+        Ok(())
+    }
+
+    fn pop(&self) -> Option<PendingUpload> {
+        self.entries
+            .lock()
+            .expect("entries lock poisoned")
+            .pop_front()
+    }
+
+    /// Rewrites the journal from the current in-memory queue. Called after a
+    /// confirmed upload, so the entry that was just popped is not replayed
+    /// on the next restart.
+    fn persist(&self) -> Result<(), Box<dyn Error>> {
+        let entries = self.entries.lock().expect("entries lock poisoned");
+        let mut journal = self.journal.lock().expect("journal lock poisoned");
+
+        let tmp_path = self.journal_path.with_extension("journal.tmp");
+        {
+            let mut tmp = File::create(&tmp_path)?;
+            for entry in entries.iter() {
+                writeln!(tmp, "{}", entry.to_line())?;
+            }
+        }
+        fs::rename(&tmp_path, &self.journal_path)?;
+        *journal = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.journal_path)?;
+        Ok(())
+    }
+}
+
+/// Drains `queue` forever, uploading each entry with `uploader` and retrying
+/// with exponential backoff (capped at `MAX_BACKOFF`) on transient failures.
+/// An entry is only removed from the journal once its upload is confirmed.
+pub fn spawn_worker(
+    queue: Arc<UploadQueue>,
+    uploader: Arc<Mutex<Uploader>>,
+) -> std::io::Result<thread::JoinHandle<()>> {
+    thread::Builder::new()
+        .name("upload-queue-worker".into())
+        .spawn(move || loop {
+            let pending = match queue.pop() {
+                Some(pending) => pending,
+                None => {
+                    thread::sleep(Duration::from_millis(500));
+                    continue;
+                }
+            };
+
+            let mut backoff = INITIAL_BACKOFF;
+            loop {
+                let result = {
+                    let uploader = uploader.lock().expect("uploader lock poisoned");
+                    uploader.upload(&pending.local_path, &pending.remote_path)
+                };
+                match result {
+                    Ok(()) => {
+                        debug!("uploaded {:?}", pending.local_path);
+                        if let Err(err) = queue.persist() {
+                            error!("could not persist upload queue journal: {}", err);
+                        }
+                        break;
+                    }
+                    Err(err) => {
+                        warn!(
+                            "upload of {:?} failed ({}), retrying in {:?}",
+                            pending.local_path, err, backoff
+                        );
+                        thread::sleep(backoff);
+                        backoff = (backoff * 2).min(MAX_BACKOFF);
+                    }
+                }
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn temp_journal(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "nud_upload_queue_test_{}_{}.journal",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn pending(remote_path: &str) -> PendingUpload {
+        PendingUpload {
+            local_path: PathBuf::from(format!("/tmp/{}", remote_path)),
+            remote_path: remote_path.to_string(),
+        }
+    }
+
+    #[test]
+    fn open_replays_a_journal_left_by_a_previous_run() {
+        let path = temp_journal("replay");
+        fs::write(&path, "a.txt\t/tmp/a.txt\nb.txt\t/tmp/b.txt\n").expect("failed to seed journal");
+
+        let queue = UploadQueue::open(path.clone()).expect("failed to open queue");
+        assert_eq!(queue.pop().map(|p| p.remote_path), Some("a.txt".to_string()));
+        assert_eq!(queue.pop().map(|p| p.remote_path), Some("b.txt".to_string()));
+        assert!(queue.pop().is_none());
+
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn persist_trims_the_journal_to_what_remains_queued() {
+        let path = temp_journal("persist_trim");
+        let queue = UploadQueue::open(path.clone()).expect("failed to open queue");
+
+        queue.enqueue(pending("a.txt")).expect("enqueue failed");
+        queue.enqueue(pending("b.txt")).expect("enqueue failed");
+
+        // Simulate the first entry's upload completing and being persisted.
+        queue.pop();
+        queue.persist().expect("persist failed");
+
+        let reopened = UploadQueue::open(path.clone()).expect("failed to reopen queue");
+        assert_eq!(reopened.pop().map(|p| p.remote_path), Some("b.txt".to_string()));
+        assert!(reopened.pop().is_none());
+
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn enqueue_during_an_in_flight_retry_is_not_lost() {
+        let path = temp_journal("enqueue_during_retry");
+        let queue = UploadQueue::open(path.clone()).expect("failed to open queue");
+
+        queue.enqueue(pending("a.txt")).expect("enqueue failed");
+
+        // Pop the first entry as if a worker picked it up for an in-flight
+        // upload, then enqueue a second one before the first is confirmed
+        // and persisted. The second entry's journal line must survive the
+        // persist() rewrite below.
+        queue.pop();
+        queue.enqueue(pending("b.txt")).expect("enqueue failed");
+        queue.persist().expect("persist failed");
+
+        let reopened = UploadQueue::open(path.clone()).expect("failed to reopen queue");
+        assert_eq!(reopened.pop().map(|p| p.remote_path), Some("b.txt".to_string()));
+        assert!(reopened.pop().is_none());
+
+        fs::remove_file(&path).ok();
+    }
+}
@@ -0,0 +1,136 @@
+use log::{debug, info};
+use reqwest::blocking::Client;
+use reqwest::{Method, StatusCode};
+use std::error::Error;
+use std::fs::File;
+use std::path::Path;
+
+use crate::configuration::Configuration;
+use crate::error::Error as CrateError;
+
+/// Talks to a Nextcloud server's WebDAV endpoint to upload files that the
+/// watcher has picked up.
+pub struct Uploader {
+    address: String,
+    username: String,
+    password: String,
+    client: Client,
+}
+
+impl Uploader {
+    pub fn new(config: &Configuration) -> Result<Self, CrateError> {
+        let password = config
+            .resolve_password()
+            .map_err(|err| CrateError::AuthFailed(err.to_string()))?;
+        Ok(Uploader {
+            address: config.address.trim_end_matches('/').to_string(),
+            username: config.username.clone(),
+            password,
+            client: Client::new(),
+        })
+    }
+
+    fn dav_url(&self, remote_path: &str) -> String {
+        format!(
+            "{}/remote.php/dav/files/{}/{}",
+            self.address, self.username, remote_path
+        )
+    }
+
+    /// Performs a one-time authenticated `PROPFIND` against the Nextcloud
+    /// files root, so startup fails fast with a clear message instead of the
+    /// daemon silently failing every upload later on.
+    pub fn verify_connection(&self) -> Result<(), CrateError> {
+        let url = self.dav_url("");
+        let method = Method::from_bytes(b"PROPFIND").expect("PROPFIND is a valid HTTP method");
+        let response = self
+            .client
+            .request(method, &url)
+            .header("Depth", "0")
+            .basic_auth(&self.username, Some(&self.password))
+            .send()?;
+
+        match response.status() {
+            StatusCode::OK | StatusCode::MULTI_STATUS => Ok(()),
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Err(CrateError::AuthFailed(
+                format!("Nextcloud rejected the credentials for {}", self.username),
+            )),
+            status => Err(CrateError::AuthFailed(format!(
+                "unexpected response from {}: {}",
+                url, status
+            ))),
+        }
+    }
+
+    /// Sends `MKCOL` for every parent path segment of `remote_path`, so the
+    /// subsequent `PUT` always lands in an existing collection. A 405
+    /// ("already exists") is not an error.
+    fn ensure_remote_collections(&self, remote_path: &str) -> Result<(), CrateError> {
+        let method = Method::from_bytes(b"MKCOL").expect("MKCOL is a valid HTTP method");
+        let mut segments: Vec<&str> = remote_path.split('/').collect();
+        segments.pop(); // last segment is the file itself, not a collection
+
+        let mut accumulated = String::new();
+        for segment in segments {
+            if segment.is_empty() {
+                continue;
+            }
+            if !accumulated.is_empty() {
+                accumulated.push('/');
+            }
+            accumulated.push_str(segment);
+
+            let url = self.dav_url(&accumulated);
+            debug!("ensuring remote collection exists: {}", url);
+            let response = self
+                .client
+                .request(method.clone(), &url)
+                .basic_auth(&self.username, Some(&self.password))
+                .send()?;
+
+            match response.status() {
+                StatusCode::CREATED | StatusCode::METHOD_NOT_ALLOWED => {}
+                status => {
+                    return Err(CrateError::UploadFailed(format!(
+                        "MKCOL {} failed: {}",
+                        url, status
+                    )))
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Uploads `local_path` to `remote_path` (relative to the user's
+    /// Nextcloud files root), creating any missing parent collections first.
+    pub fn upload(&self, local_path: &Path, remote_path: &str) -> Result<(), CrateError> {
+        self.ensure_remote_collections(remote_path)?;
+
+        let url = self.dav_url(remote_path);
+        info!("uploading {:?} to {}", local_path, url);
+        let file = File::open(local_path).map_err(|err| {
+            CrateError::UploadFailed(format!("could not open {:?} for upload: {}", local_path, err))
+        })?;
+        let response = self
+            .client
+            .put(&url)
+            .basic_auth(&self.username, Some(&self.password))
+            .body(file)
+            .send()?;
+
+        match response.status() {
+            StatusCode::OK | StatusCode::CREATED | StatusCode::NO_CONTENT => Ok(()),
+            status => Err(CrateError::UploadFailed(format!(
+                "upload of {:?} failed: {}",
+                local_path, status
+            ))),
+        }
+    }
+}
+
+/// Maps a path under the watched root to the remote path it should be
+/// uploaded to, by stripping the watched-root prefix.
+pub fn remote_path_for(local_path: &Path, watched_root: &Path) -> Result<String, Box<dyn Error>> {
+    let relative = local_path.strip_prefix(watched_root)?;
+    Ok(relative.to_string_lossy().replace('\\', "/"))
+}
@@ -1,27 +1,233 @@
+use log::{debug, error, warn};
+use notify::event::{AccessKind, AccessMode, EventKind};
 use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
-use std::path::Path;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
 
-fn event_dispatcher(event: Event) {
-    if let notify::event::EventKind::Access(notify::event::AccessKind::Close(
-        notify::event::AccessMode::Write,
-    )) = event.kind
-    {
-        println!("Close of write file event: {:?}", event);
+use crate::error::Error;
+use crate::filter::PathFilter;
+use crate::upload_queue::{PendingUpload, UploadQueue};
+use crate::uploader;
+
+/// Tracks which paths under the watched root are currently being watched by
+/// `notify`, which ones don't exist yet (or failed to canonicalize) but
+/// might still appear later, and which ones can never be watched (e.g.
+/// beyond the filesystem root). This lets the daemon cope with directories
+/// appearing or disappearing instead of crashing.
+#[derive(Debug, Default)]
+pub struct WatchState {
+    pub watching: HashSet<PathBuf>,
+    pub pending: HashSet<PathBuf>,
+    pub invalid: HashSet<PathBuf>,
+}
+
+impl WatchState {
+    pub fn new() -> Self {
+        WatchState::default()
+    }
+
+    /// Tries to start watching `path`. On success it is promoted out of
+    /// `pending` and into `watching`; if the path does not exist (yet) it is
+    /// recorded as `pending` so it can be retried later instead of failing
+    /// outright. A path with no parent left to strip is `invalid` and is not
+    /// retried again.
+    pub fn watch(&mut self, watcher: &mut RecommendedWatcher, path: &Path) {
+        if self.invalid.contains(path) || self.watching.contains(path) {
+            return;
+        }
+        if path.parent().is_none() {
+            warn!("{:?} is beyond the filesystem root, will never be watchable", path);
+            self.pending.remove(path);
+            self.invalid.insert(path.to_path_buf());
+            return;
+        }
+        match path.canonicalize() {
+            Ok(canonical) => match watcher.watch(&canonical, RecursiveMode::Recursive) {
+                Ok(()) => {
+                    debug!("now watching {:?}", canonical);
+                    self.pending.remove(path);
+                    self.watching.insert(canonical);
+                }
+                Err(err) => {
+                    warn!("could not watch {:?}: {}", canonical, err);
+                    self.pending.insert(path.to_path_buf());
+                }
+            },
+            Err(_) => {
+                debug!("{:?} does not exist yet, will retry later", path);
+                self.pending.insert(path.to_path_buf());
+            }
+        }
+    }
+
+    /// Stops watching `path`, if it was watched, and marks it `pending` so it
+    /// is picked up again if it reappears. `watching` stores canonicalized
+    /// paths (see `watch`), so `path` is canonicalized the same way before
+    /// the lookup; if `path` no longer exists (the usual case for a removal
+    /// event) canonicalization fails and `path` is used as-is, since in that
+    /// case it already came from a previously-canonicalized watch.
+    pub fn forget(&mut self, watcher: &mut RecommendedWatcher, path: &Path) {
+        let resolved = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        if self.watching.remove(&resolved) {
+            if let Err(err) = watcher.unwatch(&resolved) {
+                warn!("could not unwatch {:?}: {}", resolved, err);
+            }
+        }
+        self.pending.insert(resolved);
+    }
+
+    /// Re-evaluates every `pending` path, promoting any that now exist.
+    pub fn retry_pending(&mut self, watcher: &mut RecommendedWatcher) {
+        let candidates: Vec<PathBuf> = self.pending.iter().cloned().collect();
+        for path in candidates {
+            self.watch(watcher, &path);
+        }
     }
 }
 
+/// Sets up a `notify` watcher that forwards every event over an mpsc
+/// channel, so the caller can run a blocking `loop { rx.recv() }` instead of
+/// reacting from inside the watcher's callback thread.
 pub fn create_watcher(
     directory_path: &str,
-) -> Result<RecommendedWatcher, Box<dyn std::error::Error>> {
-    // Automatically select the best implementation for your platform.
-    let mut dir_watcher = notify::recommended_watcher(|res| match res {
-        Ok(event) => event_dispatcher(event),
-        Err(e) => println!("watch error: {:?}", e),
+) -> Result<(RecommendedWatcher, Receiver<Event>), Error> {
+    let (tx, rx) = mpsc::channel();
+
+    let dir_watcher = notify::recommended_watcher(move |res| match res {
+        Ok(event) => {
+            if tx.send(event).is_err() {
+                error!("event receiver dropped, discarding watch event");
+            }
+        }
+        Err(e) => error!("watch error: {:?}", e),
     })?;
 
-    // Add a path to be watched. All files and directories at that path and
-    // below will be monitored for changes.
-    dir_watcher.watch(Path::new(directory_path), RecursiveMode::Recursive)?;
+    debug!("watcher created for {}", directory_path);
+    Ok((dir_watcher, rx))
+}
+
+/// Reacts to a single filesystem event: enqueues closed files for upload,
+/// and keeps `state` in sync when directories are created or removed under
+/// the watched tree.
+pub fn event_dispatcher(
+    event: &Event,
+    watched_root: &Path,
+    queue: &UploadQueue,
+    filter: &PathFilter,
+    state: &mut WatchState,
+    watcher: &mut RecommendedWatcher,
+) {
+    match &event.kind {
+        EventKind::Access(AccessKind::Close(AccessMode::Write)) => {
+            for path in &event.paths {
+                match uploader::remote_path_for(path, watched_root) {
+                    Ok(remote_path) => {
+                        if !filter.allows(&remote_path) {
+                            continue;
+                        }
+                        let pending = PendingUpload {
+                            local_path: path.clone(),
+                            remote_path,
+                        };
+                        if let Err(err) = queue.enqueue(pending) {
+                            error!("failed to enqueue {:?} for upload: {}", path, err);
+                        }
+                    }
+                    Err(err) => error!("could not determine remote path for {:?}: {}", path, err),
+                }
+            }
+        }
+        EventKind::Create(_) => state.retry_pending(watcher),
+        EventKind::Remove(_) => {
+            for path in &event.paths {
+                state.forget(watcher, path);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::fs;
+
+    fn watcher() -> RecommendedWatcher {
+        notify::recommended_watcher(|_: notify::Result<Event>| {}).expect("failed to create watcher")
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("nud_watcher_test_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).expect("failed to create temp dir");
+        dir
+    }
+
+    #[test]
+    fn watch_promotes_an_existing_directory_out_of_pending() {
+        let dir = temp_dir("watch_existing");
+        let mut watcher = watcher();
+        let mut state = WatchState::new();
+
+        state.watch(&mut watcher, &dir);
+
+        assert!(state.watching.contains(&dir.canonicalize().unwrap()));
+        assert!(state.pending.is_empty());
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn watch_defers_a_path_that_does_not_exist_yet() {
+        let dir = temp_dir("watch_missing");
+        let missing = dir.join("not-there-yet");
+        let mut watcher = watcher();
+        let mut state = WatchState::new();
+
+        state.watch(&mut watcher, &missing);
+
+        assert!(state.pending.contains(&missing));
+        assert!(state.watching.is_empty());
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn forget_removes_a_watched_path_and_marks_it_pending() {
+        let dir = temp_dir("forget_watched");
+        let mut watcher = watcher();
+        let mut state = WatchState::new();
+
+        state.watch(&mut watcher, &dir);
+        let canonical = dir.canonicalize().unwrap();
+        assert!(state.watching.contains(&canonical));
+
+        state.forget(&mut watcher, &dir);
+
+        assert!(!state.watching.contains(&canonical));
+        assert!(state.pending.contains(&canonical));
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn forget_handles_a_path_that_no_longer_exists() {
+        let dir = temp_dir("forget_removed");
+        let mut watcher = watcher();
+        let mut state = WatchState::new();
+
+        state.watch(&mut watcher, &dir);
+        let canonical = dir.canonicalize().unwrap();
+        fs::remove_dir_all(&dir).expect("failed to remove temp dir");
 
-    Ok(dir_watcher)
+        // Once `dir` no longer exists, canonicalization fails inside
+        // `forget`, so it must fall back to the raw path and still match the
+        // canonicalized entry `watch` inserted earlier.
+        state.forget(&mut watcher, &canonical);
+
+        assert!(!state.watching.contains(&canonical));
+        assert!(state.pending.contains(&canonical));
+    }
 }